@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// On-disk TOML configuration, merged with command-line flags at startup so
+/// credentials don't need to be passed as bare arguments (and end up in
+/// shell history or process listings).
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+
+    #[serde(default)]
+    pub auth: AuthConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub ups_name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuthConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Config {
+    /// Loads the config file at `path`. Missing fields are left as `None`
+    /// so callers can fall back to command-line flags or other defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Loads the config file at `path` if given, otherwise falls back to the
+    /// default lookup path (`~/.config/upsman/config.toml`). Returns the
+    /// default (empty) config if no path is given and the default file does
+    /// not exist; a given path that cannot be read is an error.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => match default_path() {
+                Some(path) if path.exists() => Self::load(&path),
+                _ => Ok(Config::default()),
+            },
+        }
+    }
+}
+
+/// The default config file location: `~/.config/upsman/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("upsman").join("config.toml"))
+}
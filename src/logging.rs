@@ -0,0 +1,23 @@
+//! Sets up structured logging for `monitor` mode. When built with the
+//! `systemd` feature and stderr is attached to the journal (systemd sets
+//! `JOURNAL_STREAM` for services it supervises), log records go to journald
+//! with their fields (`ups`, `status`, ...) preserved as structured data.
+//! Otherwise records are formatted as plain text on stderr.
+pub fn init() {
+    #[cfg(feature = "systemd")]
+    {
+        use tracing_subscriber::prelude::*;
+
+        if std::env::var_os("JOURNAL_STREAM").is_some() {
+            if let Ok(layer) = tracing_journald::layer() {
+                tracing_subscriber::registry().with(layer).init();
+                return;
+            }
+        }
+    }
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .without_time()
+        .init();
+}
@@ -1,23 +1,37 @@
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use rups::{blocking::Connection, Auth, ConfigBuilder};
+use serde::Serialize;
+
+mod config;
+mod logging;
+mod monitor;
+mod retry;
+mod systemd;
 
 #[derive(Debug, Parser)]
 #[clap(version)]
 struct Opt {
+    /// Path to a TOML config file. Defaults to
+    /// `~/.config/upsman/config.toml` if present. Command-line flags take
+    /// precedence over anything set in the file.
+    #[clap(long, short = 'c')]
+    config: Option<PathBuf>,
+
     /// NUT UPS server host name
     #[clap(long, short)]
-    server: String,
+    server: Option<String>,
 
     /// NUT UPS server TCP port
     #[clap(long, short)]
-    port: u16,
+    port: Option<u16>,
 
     /// Name of the UPS
     #[clap(long, short)]
-    ups_name: String,
+    ups_name: Option<String>,
 
     /// NUT server user name that has the permission to run INSTCMD
     #[clap(long, short = 'n')]
@@ -31,11 +45,84 @@ struct Opt {
     #[clap(long, short, action)]
     debug: bool,
 
+    /// Output format for commands that produce data (e.g. `usage`)
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Number of times to retry a connection or command after a transient
+    /// (network/timeout) failure before giving up
+    #[clap(long, default_value = "3")]
+    retries: u32,
+
+    /// Seconds to wait before the first retry; doubles after each
+    /// subsequent attempt
+    #[clap(long, default_value = "2")]
+    retry_delay: u64,
+
     /// Command to run,
     #[clap(subcommand)]
     command: Command,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Human-readable lines, one per requested value
+    Text,
+
+    /// JSON: one object with a field per requested value for `usage`, one
+    /// object per variable for `get-var`, and an array of them for `list-vars`
+    Json,
+}
+
+impl Opt {
+    /// Fills any field not given on the command line from the loaded config
+    /// file.
+    fn merge_config(&mut self, config: &config::Config) {
+        if self.server.is_none() {
+            self.server = config.server.host.clone();
+        }
+        if self.port.is_none() {
+            self.port = config.server.port;
+        }
+        if self.ups_name.is_none() {
+            self.ups_name = config.server.ups_name.clone();
+        }
+        if self.username.is_none() {
+            self.username = config.auth.username.clone();
+        }
+        if self.password.is_none() {
+            self.password = config.auth.password.clone();
+        }
+    }
+
+    /// Checks that every field required to connect to the NUT server was
+    /// supplied, either on the command line or via the config file.
+    fn validate(&self) -> Result<()> {
+        if self.server.is_none() {
+            return Err(anyhow!("Missing required value: server (use --server or set [server].host in the config file)"));
+        }
+        if self.port.is_none() {
+            return Err(anyhow!("Missing required value: port (use --port or set [server].port in the config file)"));
+        }
+        if self.ups_name.is_none() {
+            return Err(anyhow!("Missing required value: ups_name (use --ups-name or set [server].ups_name in the config file)"));
+        }
+        Ok(())
+    }
+
+    fn server(&self) -> &str {
+        self.server.as_deref().expect("validated at startup")
+    }
+
+    fn port(&self) -> u16 {
+        self.port.expect("validated at startup")
+    }
+
+    fn ups_name(&self) -> &str {
+        self.ups_name.as_deref().expect("validated at startup")
+    }
+}
+
 #[derive(Debug, Subcommand, PartialEq, PartialOrd, Eq, Ord)]
 enum Command {
     /// Turn load off on UPS
@@ -49,9 +136,54 @@ enum Command {
         /// Allowed values: voltage_in, voltage_out, current_out, power
         usage_types: Vec<UsageType>,
     },
+
+    /// Run as a long-lived daemon that watches for UPS power state
+    /// transitions and runs hook scripts in response
+    Monitor {
+        /// Seconds to wait between polls of the UPS status
+        #[clap(long, default_value = "5")]
+        poll_interval: u64,
+
+        /// Command to run when the UPS switches to battery power
+        #[clap(long = "on-battery")]
+        on_battery: Option<String>,
+
+        /// Command to run when the UPS returns to line power
+        #[clap(long = "on-line")]
+        on_line: Option<String>,
+
+        /// Command to run when the UPS reports a low battery
+        #[clap(long = "low-battery")]
+        low_battery: Option<String>,
+
+        /// Command to run when the UPS reports its battery needs replacing
+        #[clap(long = "replace-battery")]
+        replace_battery: Option<String>,
+    },
+
+    /// List every NUT variable the UPS reports, with its value and description
+    ListVars,
+
+    /// Get the value of a single NUT variable, e.g. `battery.charge` or
+    /// `ups.status`. The `power` pseudo-variable is also accepted as a
+    /// convenience alias for `output.voltage * output.current`.
+    GetVar {
+        /// NUT variable name, or `power`
+        name: String,
+    },
+
+    /// Set a writable NUT variable to a new value [unsupported: requires
+    /// NUT SET VAR, not available in the pinned rups client]
+    SetVar {
+        /// NUT variable name
+        name: String,
+
+        /// New value to assign
+        value: String,
+    },
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
 enum UsageType {
     VoltageIn,
     VoltageOut,
@@ -85,50 +217,145 @@ impl From<&UsageType> for &'static str {
 }
 
 fn main() -> Result<()> {
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+
+    let file_config = config::Config::load_or_default(opt.config.as_deref())?;
+    opt.merge_config(&file_config);
+    opt.validate()?;
 
     let auth = opt
         .username
         .as_ref()
-        .map(|username| Auth::new(username.clone(), opt.password.as_ref().map(Clone::clone)));
+        .map(|username| Auth::new(username.clone(), opt.password.clone()));
     let config = ConfigBuilder::new()
-        .with_host((opt.server.clone(), opt.port).try_into()?)
+        .with_host((opt.server().to_string(), opt.port()).try_into()?)
         .with_auth(auth)
         .with_debug(opt.debug)
         .build();
-    let mut connection = Connection::new(&config)?;
+
+    if let Command::Monitor {
+        poll_interval,
+        ref on_battery,
+        ref on_line,
+        ref low_battery,
+        ref replace_battery,
+    } = opt.command
+    {
+        logging::init();
+        let hooks = monitor::Hooks {
+            on_battery: on_battery.clone(),
+            on_line: on_line.clone(),
+            low_battery: low_battery.clone(),
+            replace_battery: replace_battery.clone(),
+        };
+        return monitor::run(&config, &opt, poll_interval, &hooks);
+    }
+
+    let mut connection = retry::connect(&config, &opt)?;
 
     match opt.command {
         Command::LoadOn => load_on(&mut connection, &opt)?,
         Command::LoadOff => load_off(&mut connection, &opt)?,
-        Command::Usage { ref usage_types } => usage(&mut connection, &opt, usage_types)?,
+        Command::Usage { ref usage_types } => {
+            usage(&mut connection, &opt, usage_types, opt.format)?
+        }
+        Command::ListVars => list_vars(&mut connection, &opt, opt.format)?,
+        Command::GetVar { ref name } => get_var(&mut connection, &opt, name, opt.format)?,
+        Command::SetVar { ref name, ref value } => set_var(&mut connection, &opt, name, value)?,
+        Command::Monitor { .. } => unreachable!("handled above"),
     }
 
     Ok(())
 }
 
 fn load_on(connection: &mut Connection, opt: &Opt) -> Result<()> {
-    Ok(connection.run_command(&opt.ups_name, Some("load.on"))?)
+    retry::with_retry(opt, || connection.run_command(opt.ups_name(), Some("load.on")))
 }
 
 fn load_off(connection: &mut Connection, opt: &Opt) -> Result<()> {
-    Ok(connection.run_command(&opt.ups_name, Some("load.off"))?)
+    retry::with_retry(opt, || connection.run_command(opt.ups_name(), Some("load.off")))
+}
+
+/// A single numeric measurement with its unit, used for `--format json`
+/// output so values are real numbers rather than text scraped from NUT's
+/// `name: value` variable responses.
+#[derive(Debug, Serialize)]
+struct Measurement {
+    value: f64,
+    unit: &'static str,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct UsageReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_voltage: Option<Measurement>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_voltage: Option<Measurement>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_current: Option<Measurement>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power_w: Option<Measurement>,
 }
 
-fn usage(connection: &mut Connection, opt: &Opt, usage_types: &Vec<UsageType>) -> Result<()> {
+fn usage(
+    connection: &mut Connection,
+    opt: &Opt,
+    usage_types: &Vec<UsageType>,
+    format: Format,
+) -> Result<()> {
+    let mut report = UsageReport::default();
+
     for ut in usage_types {
         if *ut == UsageType::Power {
             let voltage_out = parse_var::<f64>(connection, opt, &UsageType::VoltageOut)?;
             let current_out = parse_var::<f64>(connection, opt, &UsageType::CurrentOut)?;
             let power = voltage_out * current_out;
-            println!("power: {power:.2} W");
+
+            match format {
+                Format::Text => println!("power: {power:.2} W"),
+                Format::Json => {
+                    report.power_w = Some(Measurement {
+                        value: power,
+                        unit: "W",
+                    })
+                }
+            }
         } else {
-            let var_name = ut.into();
-            let var_value = connection.get_var(&opt.ups_name, var_name)?;
-            println!("{var_value}");
+            match format {
+                Format::Text => {
+                    let var_name = ut.into();
+                    let var_value =
+                        retry::with_retry(opt, || connection.get_var(opt.ups_name(), var_name))?;
+                    println!("{var_value}");
+                }
+                Format::Json => {
+                    let value = parse_var::<f64>(connection, opt, ut)?;
+                    let measurement = Measurement {
+                        value,
+                        unit: match ut {
+                            UsageType::VoltageIn | UsageType::VoltageOut => "V",
+                            UsageType::CurrentOut => "A",
+                            UsageType::Power => unreachable!(),
+                        },
+                    };
+                    match ut {
+                        UsageType::VoltageIn => report.input_voltage = Some(measurement),
+                        UsageType::VoltageOut => report.output_voltage = Some(measurement),
+                        UsageType::CurrentOut => report.output_current = Some(measurement),
+                        UsageType::Power => unreachable!(),
+                    }
+                }
+            }
         }
     }
 
+    if format == Format::Json {
+        println!("{}", serde_json::to_string(&report)?);
+    }
+
     Ok(())
 }
 
@@ -138,11 +365,107 @@ fn parse_var<T: FromStr>(
     usage_type: &UsageType,
 ) -> Result<T> {
     let usage_type = usage_type.into();
-    Ok(connection
-        .get_var(&opt.ups_name, usage_type)?
-        .to_string()
-        .splitn(2, ": ")
-        .nth(1)
-        .and_then(|v| v.parse().ok())
-        .ok_or_else(|| anyhow!("Variable {} not found", usage_type))?)
+    let var = retry::with_retry(opt, || connection.get_var(opt.ups_name(), usage_type))?;
+    var.value()
+        .parse()
+        .map_err(|_| anyhow!("Variable {} does not have a numeric value", usage_type))
+}
+
+/// A NUT variable's value as JSON: a real number when the value parses as
+/// one (as most NUT variables do), the raw string otherwise (e.g.
+/// `ups.status`, which is a flag set like `"OL"`).
+fn var_value_json(value: &str) -> serde_json::Value {
+    match value.parse::<f64>() {
+        Ok(n) => serde_json::json!(n),
+        Err(_) => serde_json::Value::String(value.to_string()),
+    }
+}
+
+/// A NUT variable along with the unit/description NUT returns for it, so
+/// `list-vars` output is self-documenting.
+#[derive(Debug, Serialize)]
+struct VarInfo {
+    name: String,
+    value: serde_json::Value,
+    description: String,
+}
+
+fn list_vars(connection: &mut Connection, opt: &Opt, format: Format) -> Result<()> {
+    let vars = retry::with_retry(opt, || connection.list_vars(opt.ups_name()))?;
+    let mut infos = Vec::with_capacity(vars.len());
+
+    for var in vars {
+        let description =
+            retry::with_retry(opt, || connection.get_var_description(opt.ups_name(), var.name()))?;
+        infos.push(VarInfo {
+            name: var.name().to_string(),
+            value: var_value_json(&var.value()),
+            description,
+        });
+    }
+
+    match format {
+        Format::Text => {
+            for info in &infos {
+                println!("{}: {} ({})", info.name, info.value, info.description);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(&infos)?),
+    }
+
+    Ok(())
+}
+
+/// Fetches a single NUT variable by name. `power` is handled specially as a
+/// convenience: it is derived client-side from `output.voltage *
+/// output.current` rather than being a real NUT variable.
+fn get_var(connection: &mut Connection, opt: &Opt, name: &str, format: Format) -> Result<()> {
+    if name == "power" {
+        let voltage_out = parse_var::<f64>(connection, opt, &UsageType::VoltageOut)?;
+        let current_out = parse_var::<f64>(connection, opt, &UsageType::CurrentOut)?;
+        let power = voltage_out * current_out;
+
+        return match format {
+            Format::Text => {
+                println!("power: {power:.2} W");
+                Ok(())
+            }
+            Format::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string(&Measurement {
+                        value: power,
+                        unit: "W",
+                    })?
+                );
+                Ok(())
+            }
+        };
+    }
+
+    let var = retry::with_retry(opt, || connection.get_var(opt.ups_name(), name))?;
+    match format {
+        Format::Text => println!("{var}"),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string(&VarInfo {
+                name: name.to_string(),
+                value: var_value_json(&var.value()),
+                description: String::new(),
+            })?
+        ),
+    }
+
+    Ok(())
+}
+
+/// Handles `set-var`. Always returns an error and changes nothing on the
+/// server: the pinned `rups` client only speaks `GET`/`LIST`/`INSTCMD`, not the NUT `SET VAR` command,
+/// and its low-level command plumbing is private to that crate, so there is
+/// no protocol call this function can issue, retried or otherwise.
+fn set_var(_connection: &mut Connection, _opt: &Opt, name: &str, _value: &str) -> Result<()> {
+    Err(anyhow!(
+        "Cannot set `{name}`: the rups client library this build uses does not implement the \
+         NUT SET VAR command (only INSTCMD, via `load-on`/`load-off`, is supported)"
+    ))
 }
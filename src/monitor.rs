@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+use std::process::Command as ProcessCommand;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rups::{blocking::Connection, Config};
+
+use crate::{systemd, Opt};
+
+/// Commands to run when the UPS `ups.status` flag set transitions into a
+/// new state.
+pub struct Hooks {
+    pub on_battery: Option<String>,
+    pub on_line: Option<String>,
+    pub low_battery: Option<String>,
+    pub replace_battery: Option<String>,
+}
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Runs the long-lived monitor loop: polls `ups.status` on `poll_interval`
+/// seconds, diffs the flag set against the previous poll and fires the
+/// matching hook script on each transition (debounced so a hook only runs
+/// once per entry into that state). Reconnects with exponential backoff if
+/// the NUT server connection drops, and exits cleanly on SIGINT/SIGTERM.
+pub fn run(config: &Config, opt: &Opt, poll_interval: u64, hooks: &Hooks) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+            .context("Failed to install SIGINT/SIGTERM handler")?;
+    }
+
+    let mut prev_status: Option<HashSet<String>> = None;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut notified_ready = false;
+    let watchdog_interval = systemd::watchdog_interval();
+    let mut since_watchdog = Duration::ZERO;
+
+    while running.load(Ordering::SeqCst) {
+        let mut connection = match Connection::new(config) {
+            Ok(connection) => connection,
+            Err(err) => {
+                tracing::warn!(
+                    ups = opt.ups_name(),
+                    server = opt.server(),
+                    port = opt.port(),
+                    backoff_secs = backoff.as_secs(),
+                    "failed to connect: {err}. retrying...",
+                );
+                sleep_while_running(&running, backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        backoff = INITIAL_BACKOFF;
+
+        if !notified_ready {
+            systemd::notify_ready();
+            notified_ready = true;
+        }
+
+        while running.load(Ordering::SeqCst) {
+            match poll_status(&mut connection, opt) {
+                Ok(current) => {
+                    if let Some(interval) = watchdog_interval {
+                        since_watchdog += Duration::from_secs(poll_interval);
+                        if since_watchdog >= interval {
+                            systemd::notify_watchdog();
+                            since_watchdog = Duration::ZERO;
+                        }
+                    } else {
+                        systemd::notify_watchdog();
+                    }
+                    if let Some(prev) = &prev_status {
+                        handle_transition(prev, &current, opt, hooks);
+                    }
+                    prev_status = Some(current);
+                }
+                Err(err) => {
+                    tracing::warn!(ups = opt.ups_name(), "lost connection: {err}");
+                    break;
+                }
+            }
+
+            sleep_while_running(&running, Duration::from_secs(poll_interval));
+        }
+    }
+
+    Ok(())
+}
+
+fn poll_status(connection: &mut Connection, opt: &Opt) -> Result<HashSet<String>> {
+    let status = connection.get_var(opt.ups_name(), "ups.status")?;
+    Ok(status
+        .value()
+        .trim_matches('"')
+        .split_whitespace()
+        .map(str::to_string)
+        .collect())
+}
+
+fn handle_transition(prev: &HashSet<String>, current: &HashSet<String>, opt: &Opt, hooks: &Hooks) {
+    let prev_status = join_status(prev);
+    let new_status = join_status(current);
+
+    if new_status != prev_status {
+        tracing::info!(
+            ups = opt.ups_name(),
+            status = new_status.as_str(),
+            prev_status = prev_status.as_str(),
+            "ups.status changed",
+        );
+    }
+
+    let fire = |flag: &str, hook: &Option<String>| {
+        if current.contains(flag) && !prev.contains(flag) {
+            spawn_hook(hook, opt, &new_status, &prev_status);
+        }
+    };
+    fire("OB", &hooks.on_battery);
+    fire("OL", &hooks.on_line);
+    fire("LB", &hooks.low_battery);
+    fire("RB", &hooks.replace_battery);
+}
+
+fn join_status(status: &HashSet<String>) -> String {
+    let mut flags: Vec<&str> = status.iter().map(String::as_str).collect();
+    flags.sort_unstable();
+    flags.join(" ")
+}
+
+fn spawn_hook(hook: &Option<String>, opt: &Opt, status: &str, prev_status: &str) {
+    let Some(cmd) = hook else {
+        return;
+    };
+
+    tracing::info!(
+        ups = opt.ups_name(),
+        status = status,
+        prev_status = prev_status,
+        "running hook `{cmd}`",
+    );
+
+    let result = ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .env("UPSMAN_UPS", opt.ups_name())
+        .env("UPSMAN_STATUS", status)
+        .env("UPSMAN_PREV_STATUS", prev_status)
+        .status();
+
+    match result {
+        Ok(status) if !status.success() => {
+            tracing::warn!(ups = opt.ups_name(), "hook `{cmd}` exited with {status}");
+        }
+        Err(err) => tracing::warn!(ups = opt.ups_name(), "failed to run hook `{cmd}`: {err}"),
+        Ok(_) => {}
+    }
+}
+
+fn sleep_while_running(running: &Arc<AtomicBool>, duration: Duration) {
+    let step = Duration::from_millis(200);
+    let mut elapsed = Duration::ZERO;
+    while elapsed < duration && running.load(Ordering::SeqCst) {
+        let remaining = duration - elapsed;
+        thread::sleep(step.min(remaining));
+        elapsed += step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use super::*;
+
+    fn status(flags: &[&str]) -> HashSet<String> {
+        flags.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn test_opt() -> Opt {
+        Opt::from_iter([
+            "upsman",
+            "--server",
+            "nut.example.test",
+            "--port",
+            "3493",
+            "--ups-name",
+            "test-ups",
+            "usage",
+        ])
+    }
+
+    /// A hook that appends one line to `path` each time it runs, so the
+    /// number of times `handle_transition` actually fires it can be counted.
+    fn counting_hook(path: &std::path::Path) -> Option<String> {
+        Some(format!("echo ran >> {}", path.display()))
+    }
+
+    fn run_count(path: &std::path::Path) -> usize {
+        fs::read_to_string(path)
+            .map(|contents| contents.lines().count())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn fires_only_on_entry_into_a_flag_not_on_every_poll() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("upsman-test-{}-entry", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let opt = test_opt();
+        let hooks = Hooks {
+            on_battery: counting_hook(&path),
+            on_line: None,
+            low_battery: None,
+            replace_battery: None,
+        };
+
+        // OB is already set on both polls: sustained state, should not fire.
+        handle_transition(&status(&["OB"]), &status(&["OB"]), &opt, &hooks);
+        assert_eq!(run_count(&path), 0, "hook must not fire while flag stays set");
+
+        // OB newly appears: this is the transition that should fire.
+        handle_transition(&status(&[]), &status(&["OB"]), &opt, &hooks);
+        assert_eq!(run_count(&path), 1, "hook must fire exactly once on entry");
+
+        // OB clears: the on-battery hook must not fire again on exit.
+        handle_transition(&status(&["OB"]), &status(&[]), &opt, &hooks);
+        assert_eq!(run_count(&path), 1, "hook must not fire again when flag clears");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn join_status_sorts_flags_for_stable_logging() {
+        assert_eq!(join_status(&status(&["OL", "OB"])), "OB OL");
+        assert_eq!(join_status(&status(&[])), "");
+    }
+}
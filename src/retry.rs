@@ -0,0 +1,162 @@
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rups::{blocking::Connection, ClientError, Config, NutError};
+
+use crate::Opt;
+
+/// Why a [`ClientError`] failed, and whether retrying it could possibly
+/// help.
+enum Failure {
+    /// Bad credentials or a session already authenticated. Retrying sends
+    /// the exact same rejected request again, so it never helps.
+    Auth,
+    /// The UPS name in `--ups-name`/the config file doesn't exist on the server.
+    /// Retrying won't make it exist.
+    UnknownUps,
+    /// A network/IO problem or a transient NUT protocol hiccup. May well
+    /// succeed on a later attempt.
+    Network,
+}
+
+impl Failure {
+    fn classify(err: &ClientError) -> Self {
+        match err {
+            ClientError::Nut(
+                NutError::AccessDenied
+                | NutError::InvalidPassword
+                | NutError::InvalidUsername
+                | NutError::UsernameRequired
+                | NutError::PasswordRequired
+                | NutError::AlreadyLoggedIn
+                | NutError::AlreadySetPassword
+                | NutError::AlreadySetUsername,
+            ) => Failure::Auth,
+            ClientError::Nut(NutError::UnknownUps) => Failure::UnknownUps,
+            _ => Failure::Network,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Failure::Network)
+    }
+}
+
+/// Opens a connection to the NUT server, retrying transient failures with
+/// exponential backoff.
+pub fn connect(config: &Config, opt: &Opt) -> Result<Connection> {
+    with_retry(opt, || Connection::new(config))
+}
+
+/// Runs `f`, retrying transient (network/protocol) failures up to
+/// `opt.retries` times with exponential backoff starting at
+/// `opt.retry_delay`. Auth and unknown-UPS failures are never retried, since
+/// retrying sends the server the same rejected request again. On final
+/// failure, returns a message that names the failure kind (auth, unknown
+/// UPS, or connectivity) so an operator isn't left chasing the wrong
+/// problem.
+pub fn with_retry<T>(opt: &Opt, mut f: impl FnMut() -> rups::Result<T>) -> Result<T> {
+    let mut delay = Duration::from_secs(opt.retry_delay);
+    let mut attempt = 0;
+
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                let failure = Failure::classify(&err);
+                if attempt < opt.retries && failure.is_retryable() {
+                    attempt += 1;
+                    eprintln!(
+                        "{}:{} ({}): {err}. Retrying in {delay:?}... (attempt {attempt}/{})",
+                        opt.server(),
+                        opt.port(),
+                        opt.ups_name(),
+                        opt.retries,
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    continue;
+                }
+
+                return Err(match failure {
+                    Failure::Auth => anyhow!(
+                        "Authentication failed for NUT server {}:{}: {err}. Check --username/--password.",
+                        opt.server(),
+                        opt.port(),
+                    ),
+                    Failure::UnknownUps => anyhow!(
+                        "NUT server {}:{} has no UPS named `{}`: {err}",
+                        opt.server(),
+                        opt.port(),
+                        opt.ups_name(),
+                    ),
+                    Failure::Network => anyhow!(
+                        "Could not reach NUT server {}:{} for UPS `{}`: {err}",
+                        opt.server(),
+                        opt.port(),
+                        opt.ups_name(),
+                    ),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    fn classify(err: ClientError) -> Failure {
+        Failure::classify(&err)
+    }
+
+    #[test]
+    fn classifies_auth_errors_as_auth() {
+        for err in [
+            NutError::AccessDenied,
+            NutError::InvalidPassword,
+            NutError::InvalidUsername,
+            NutError::UsernameRequired,
+            NutError::PasswordRequired,
+            NutError::AlreadyLoggedIn,
+            NutError::AlreadySetPassword,
+            NutError::AlreadySetUsername,
+        ] {
+            assert!(matches!(classify(ClientError::Nut(err)), Failure::Auth));
+        }
+    }
+
+    #[test]
+    fn classifies_unknown_ups_separately_from_auth() {
+        assert!(matches!(
+            classify(ClientError::Nut(NutError::UnknownUps)),
+            Failure::UnknownUps
+        ));
+    }
+
+    #[test]
+    fn classifies_other_nut_errors_as_network() {
+        assert!(matches!(
+            classify(ClientError::Nut(NutError::DataStale)),
+            Failure::Network
+        ));
+    }
+
+    #[test]
+    fn classifies_io_errors_as_network() {
+        assert!(matches!(
+            classify(ClientError::Io(io::Error::new(io::ErrorKind::TimedOut, "timed out"))),
+            Failure::Network
+        ));
+    }
+
+    #[test]
+    fn only_network_failures_are_retryable() {
+        assert!(Failure::Network.is_retryable());
+        assert!(!Failure::Auth.is_retryable());
+        assert!(!Failure::UnknownUps.is_retryable());
+    }
+}
@@ -0,0 +1,37 @@
+//! Thin wrappers around the systemd `sd_notify` protocol, used by `monitor`
+//! mode when running as a `Type=notify` unit. Unless the `systemd` cargo
+//! feature is enabled, the `sd_notify` calls are compiled out and these
+//! functions are no-ops, so non-Linux builds still compile.
+use std::time::Duration;
+
+/// Tells systemd the service finished starting up. No-op if not built with
+/// the `systemd` feature, or if `NOTIFY_SOCKET` isn't set (i.e. not running
+/// under systemd).
+pub fn notify_ready() {
+    #[cfg(feature = "systemd")]
+    if let Err(err) = sd_notify::notify(&[sd_notify::NotifyState::Ready]) {
+        eprintln!("Failed to notify systemd of readiness: {err}");
+    }
+}
+
+/// Pings the systemd watchdog. No-op if not built with the `systemd` feature.
+pub fn notify_watchdog() {
+    #[cfg(feature = "systemd")]
+    if let Err(err) = sd_notify::notify(&[sd_notify::NotifyState::Watchdog]) {
+        eprintln!("Failed to notify systemd watchdog: {err}");
+    }
+}
+
+/// How often to ping the watchdog so it stays well within the unit's
+/// `WatchdogSec`, or `None` if the watchdog isn't enabled for this unit.
+pub fn watchdog_interval() -> Option<Duration> {
+    #[cfg(feature = "systemd")]
+    {
+        sd_notify::watchdog_enabled().map(|d| d / 2)
+    }
+
+    #[cfg(not(feature = "systemd"))]
+    {
+        None
+    }
+}